@@ -1,3 +1,5 @@
+use std::io;
+
 use archive;
 
 pub type ArchiveResult<T> = Result<T, ArchiveError>;
@@ -5,18 +7,51 @@ pub type ArchiveResult<T> = Result<T, ArchiveError>;
 #[derive(Debug)]
 pub struct ErrCode(i32);
 
+// The return-status class libarchive reports alongside the errno. The numeric errno
+// on its own cannot tell a non-fatal warning apart from a fatal failure, so the class
+// is carried through to let callers recover from recoverable results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Warn,
+    Retry,
+    Failed,
+    Fatal,
+}
+
+impl Status {
+    pub fn from_code(code: i32) -> Status {
+        match code {
+            archive::ARCHIVE_WARN => Status::Warn,
+            archive::ARCHIVE_RETRY => Status::Retry,
+            archive::ARCHIVE_FAILED => Status::Failed,
+            _ => Status::Fatal,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum ArchiveError {
     Consumed,
-    Sys(ErrCode, String),
+    Sys(ErrCode, Status, String),
     ReadFailure,
     WriteFailure,
     HeaderPosition,
+    // An `io::Error` raised by a caller-supplied stream, carried across the FFI
+    // boundary so it is not collapsed into libarchive's generic message.
+    Io(io::Error),
+}
+
+impl ArchiveError {
+    // Build an error from a handle while preserving the libarchive return-status class
+    // of the call that failed.
+    pub fn from_status(handle: &archive::Handle, status: Status) -> ArchiveError {
+        ArchiveError::Sys(ErrCode::from(handle), status, handle.err_msg())
+    }
 }
 
 impl<'a> From<&'a archive::Handle> for ArchiveError {
     fn from(handle: &'a archive::Handle) -> ArchiveError {
-        ArchiveError::Sys(ErrCode::from(handle), handle.err_msg())
+        ArchiveError::Sys(ErrCode::from(handle), Status::Fatal, handle.err_msg())
     }
 }
 
@@ -1,16 +1,88 @@
 use std::default::Default;
 use std::ffi::CString;
+use std::io::{self, Read, Write};
+use std::os::raw::{c_int, c_long, c_void};
 use std::path::Path;
 use std::ptr;
+use std::slice;
 
 use libarchive3_sys as ffi;
 
 use crate::archive::{Entry, ExtractOptions, Handle, WriteFilter, WriteFormat};
-use crate::error::{ArchiveError, ArchiveResult};
+use crate::error::{ArchiveError, ArchiveResult, Status};
 use crate::reader::{Reader, ReaderEntry};
 
 pub struct Writer {
     handle: *mut ffi::archive,
+    // When writing to a caller-supplied stream the client state lives behind a raw
+    // pointer handed to libarchive; the `Writer` keeps ownership so it outlives the
+    // archive handle and is reclaimed in `Drop`.
+    client: Option<StreamClient>,
+}
+
+// Type-erased handle to the boxed stream state registered with `archive_write_open`.
+// `error` points at the same non-generic cell the callbacks write into, so the
+// `Writer` can recover a stashed `io::Error` without knowing the concrete `W`.
+struct StreamClient {
+    data: *mut c_void,
+    error: *mut Option<io::Error>,
+    free: unsafe fn(*mut c_void),
+}
+
+impl Drop for StreamClient {
+    fn drop(&mut self) {
+        unsafe {
+            (self.free)(self.data);
+            drop(Box::from_raw(self.error));
+        }
+    }
+}
+
+// Boxed behind the `client_data` pointer for the duration of a streaming write. The
+// write callback stashes the last `io::Error` in the shared `error` cell so it is not
+// lost across the FFI boundary.
+struct StreamData<W> {
+    writer: W,
+    error: *mut Option<io::Error>,
+}
+
+extern "C" fn stream_open_callback(_: *mut ffi::archive, _: *mut c_void) -> c_int {
+    ffi::ARCHIVE_OK
+}
+
+extern "C" fn stream_write_callback<W: Write>(
+    _: *mut ffi::archive,
+    client_data: *mut c_void,
+    buff: *const c_void,
+    length: usize,
+) -> isize {
+    let state = unsafe { &mut *(client_data as *mut StreamData<W>) };
+    let buf = unsafe { slice::from_raw_parts(buff as *const u8, length) };
+    match state.writer.write_all(buf) {
+        Ok(()) => length as isize,
+        Err(e) => {
+            unsafe { *state.error = Some(e) };
+            -1
+        }
+    }
+}
+
+extern "C" fn stream_close_callback<W: Write>(
+    _: *mut ffi::archive,
+    client_data: *mut c_void,
+) -> c_int {
+    let state = unsafe { &mut *(client_data as *mut StreamData<W>) };
+    match state.writer.flush() {
+        Ok(()) => ffi::ARCHIVE_OK,
+        Err(e) => {
+            unsafe { *state.error = Some(e) };
+            ffi::ARCHIVE_FATAL
+        }
+    }
+}
+
+unsafe fn free_stream<W>(data: *mut c_void) {
+    drop(Box::from_raw(data as *mut StreamData<W>));
 }
 
 pub struct Disk {
@@ -24,7 +96,91 @@ pub struct Builder {
 
 impl Writer {
     pub fn new(handle: *mut ffi::archive) -> Self {
-        Writer { handle: handle }
+        Writer {
+            handle: handle,
+            client: None,
+        }
+    }
+
+    fn with_client(handle: *mut ffi::archive, client: StreamClient) -> Self {
+        Writer {
+            handle: handle,
+            client: Some(client),
+        }
+    }
+
+    // Turn a failed FFI call into an error, preferring the `io::Error` stashed by the
+    // stream callbacks so the caller sees the real cause rather than libarchive's
+    // generic message.
+    fn stream_error(&self) -> ArchiveError {
+        if let Some(client) = self.client.as_ref() {
+            if let Some(err) = unsafe { (*client.error).take() } {
+                return ArchiveError::Io(err);
+            }
+        }
+        ArchiveError::from(self as &dyn Handle)
+    }
+
+    // Emit the header describing the next entry. The entry is fully described by the
+    // caller through `WriteEntry`, so this is the entry point for building an archive
+    // from synthesized data rather than copying from an existing `Reader`.
+    pub fn write_header(&mut self, entry: &WriteEntry) -> ArchiveResult<()> {
+        match unsafe { ffi::archive_write_header(self.handle, entry.entry()) } {
+            ffi::ARCHIVE_OK => Ok(()),
+            _ => Err(self.stream_error()),
+        }
+    }
+
+    // Write the body of the current entry from an in-memory buffer, looping until the
+    // whole slice has been consumed or libarchive accepts no more bytes (a write that
+    // takes 0 bytes ends the loop). Returns the number of bytes written, which can be
+    // less than `buff.len()`.
+    pub fn write_data(&mut self, buff: &[u8]) -> ArchiveResult<usize> {
+        let mut written: usize = 0;
+        while written < buff.len() {
+            let n = unsafe {
+                ffi::archive_write_data(
+                    self.handle,
+                    buff[written..].as_ptr() as *const c_void,
+                    buff.len() - written,
+                )
+            };
+            if n < 0 {
+                return Err(self.stream_error());
+            }
+            if n == 0 {
+                break;
+            }
+            written += n as usize;
+        }
+        Ok(written)
+    }
+
+    // Flush and finalize the archive, returning any error from the final block. Relying
+    // on `Drop` alone hides this: it calls `archive_write_free` and discards both the
+    // return code and the `io::Error` stashed by a stream close callback, so a failed
+    // flush to a socket or upload body would be silently swallowed. Callers that need to
+    // know the output was completed should call this before dropping the `Writer`.
+    pub fn close(&self) -> ArchiveResult<()> {
+        match unsafe { ffi::archive_write_close(self.handle()) } {
+            ffi::ARCHIVE_OK => Ok(()),
+            _ => Err(self.stream_error()),
+        }
+    }
+
+    // Write the body of the current entry by streaming it from any `Read`, so the
+    // caller never has to buffer the whole entry in memory.
+    pub fn write_data_from<R: Read>(&mut self, mut reader: R) -> ArchiveResult<usize> {
+        let mut buff = [0u8; 8192];
+        let mut written: usize = 0;
+        loop {
+            let read = reader.read(&mut buff).map_err(|_| ArchiveError::ReadFailure)?;
+            if read == 0 {
+                break;
+            }
+            written += self.write_data(&buff[..read])?;
+        }
+        Ok(written)
     }
 }
 
@@ -42,6 +198,165 @@ impl Drop for Writer {
     }
 }
 
+pub struct WriteEntry {
+    entry: *mut ffi::archive_entry,
+}
+
+impl WriteEntry {
+    pub fn new() -> Self {
+        WriteEntry::default()
+    }
+
+    pub fn set_pathname<T: AsRef<Path>>(&self, path: T) {
+        let c_path = CString::new(path.as_ref().to_string_lossy().as_bytes()).unwrap();
+        unsafe { ffi::archive_entry_set_pathname(self.entry, c_path.as_ptr()) }
+    }
+
+    pub fn set_size(&self, size: i64) {
+        unsafe { ffi::archive_entry_set_size(self.entry, size) }
+    }
+
+    pub fn set_filetype(&self, filetype: u32) {
+        unsafe { ffi::archive_entry_set_filetype(self.entry, filetype) }
+    }
+
+    pub fn set_perm(&self, perm: u32) {
+        unsafe { ffi::archive_entry_set_perm(self.entry, perm) }
+    }
+
+    pub fn set_uid(&self, uid: i64) {
+        unsafe { ffi::archive_entry_set_uid(self.entry, uid) }
+    }
+
+    pub fn set_gid(&self, gid: i64) {
+        unsafe { ffi::archive_entry_set_gid(self.entry, gid) }
+    }
+
+    // Nanosecond-resolution timestamp accessors. The matching accessors on the
+    // copy-path type live in the `impl ReaderEntry` block below.
+    pub fn atime(&self) -> i64 {
+        unsafe { ffi::archive_entry_atime(self.entry) }
+    }
+
+    pub fn atime_nsec(&self) -> i64 {
+        unsafe { ffi::archive_entry_atime_nsec(self.entry) as i64 }
+    }
+
+    pub fn set_atime(&self, sec: i64, nsec: i64) {
+        unsafe { ffi::archive_entry_set_atime(self.entry, sec, nsec as c_long) }
+    }
+
+    pub fn mtime(&self) -> i64 {
+        unsafe { ffi::archive_entry_mtime(self.entry) }
+    }
+
+    pub fn mtime_nsec(&self) -> i64 {
+        unsafe { ffi::archive_entry_mtime_nsec(self.entry) as i64 }
+    }
+
+    pub fn set_mtime(&self, sec: i64, nsec: i64) {
+        unsafe { ffi::archive_entry_set_mtime(self.entry, sec, nsec as c_long) }
+    }
+
+    pub fn ctime(&self) -> i64 {
+        unsafe { ffi::archive_entry_ctime(self.entry) }
+    }
+
+    pub fn ctime_nsec(&self) -> i64 {
+        unsafe { ffi::archive_entry_ctime_nsec(self.entry) as i64 }
+    }
+
+    pub fn set_ctime(&self, sec: i64, nsec: i64) {
+        unsafe { ffi::archive_entry_set_ctime(self.entry, sec, nsec as c_long) }
+    }
+
+    pub fn birthtime(&self) -> i64 {
+        unsafe { ffi::archive_entry_birthtime(self.entry) }
+    }
+
+    pub fn birthtime_nsec(&self) -> i64 {
+        unsafe { ffi::archive_entry_birthtime_nsec(self.entry) as i64 }
+    }
+
+    pub fn set_birthtime(&self, sec: i64, nsec: i64) {
+        unsafe { ffi::archive_entry_set_birthtime(self.entry, sec, nsec as c_long) }
+    }
+
+    pub(crate) unsafe fn entry(&self) -> *mut ffi::archive_entry {
+        self.entry
+    }
+}
+
+impl Default for WriteEntry {
+    fn default() -> Self {
+        unsafe {
+            let entry = ffi::archive_entry_new();
+            if entry.is_null() {
+                panic!("Allocation error");
+            }
+            WriteEntry { entry: entry }
+        }
+    }
+}
+
+impl Drop for WriteEntry {
+    fn drop(&mut self) {
+        unsafe {
+            ffi::archive_entry_free(self.entry);
+        }
+    }
+}
+
+// Nanosecond-resolution timestamp accessors for the read side. `ReaderEntry` is the
+// type `Disk::write` copies verbatim from: the entry, nsec fields included, is handed
+// to `archive_write_header` unchanged. These accessors let callers read or adjust
+// atime/ctime/birthtime and the mtime nsec component before that copy.
+impl ReaderEntry {
+    pub fn atime(&self) -> i64 {
+        unsafe { ffi::archive_entry_atime(self.entry()) }
+    }
+
+    pub fn atime_nsec(&self) -> i64 {
+        unsafe { ffi::archive_entry_atime_nsec(self.entry()) as i64 }
+    }
+
+    pub fn set_atime(&self, sec: i64, nsec: i64) {
+        unsafe { ffi::archive_entry_set_atime(self.entry(), sec, nsec as c_long) }
+    }
+
+    pub fn mtime_nsec(&self) -> i64 {
+        unsafe { ffi::archive_entry_mtime_nsec(self.entry()) as i64 }
+    }
+
+    pub fn set_mtime(&self, sec: i64, nsec: i64) {
+        unsafe { ffi::archive_entry_set_mtime(self.entry(), sec, nsec as c_long) }
+    }
+
+    pub fn ctime(&self) -> i64 {
+        unsafe { ffi::archive_entry_ctime(self.entry()) }
+    }
+
+    pub fn ctime_nsec(&self) -> i64 {
+        unsafe { ffi::archive_entry_ctime_nsec(self.entry()) as i64 }
+    }
+
+    pub fn set_ctime(&self, sec: i64, nsec: i64) {
+        unsafe { ffi::archive_entry_set_ctime(self.entry(), sec, nsec as c_long) }
+    }
+
+    pub fn birthtime(&self) -> i64 {
+        unsafe { ffi::archive_entry_birthtime(self.entry()) }
+    }
+
+    pub fn birthtime_nsec(&self) -> i64 {
+        unsafe { ffi::archive_entry_birthtime_nsec(self.entry()) as i64 }
+    }
+
+    pub fn set_birthtime(&self, sec: i64, nsec: i64) {
+        unsafe { ffi::archive_entry_set_birthtime(self.entry(), sec, nsec as c_long) }
+    }
+}
+
 impl Disk {
     pub fn new() -> Self {
         Disk::default()
@@ -102,11 +417,20 @@ impl Disk {
     }
 
     // * Failures - HeaderPosition
-    pub fn write<T: Reader>(&self, reader: &mut T, prefix: Option<&str>) -> ArchiveResult<usize> {
+    //
+    // A non-fatal `ARCHIVE_WARN` result (e.g. a single entry failing to restore its
+    // owner while the rest of the archive extracts cleanly) is collected and returned
+    // alongside the byte count rather than aborting the whole operation.
+    pub fn write<T: Reader>(
+        &self,
+        reader: &mut T,
+        prefix: Option<&str>,
+    ) -> ArchiveResult<(usize, Vec<ArchiveError>)> {
         if reader.header_position() != 0 {
             return Err(ArchiveError::HeaderPosition);
         }
         let mut bytes: usize = 0;
+        let mut warnings: Vec<ArchiveError> = Vec::new();
         let mut write_pending: bool = false;
         loop {
             {
@@ -119,9 +443,8 @@ impl Disk {
                             entry.set_link(&path);
                         }
                     }
-                    match self.write_header(entry) {
-                        Ok(()) => (),
-                        Err(e) => return Err(e),
+                    if let Some(warning) = self.write_header(entry)? {
+                        warnings.push(warning);
                     }
                     if entry.size() > 0 {
                         write_pending = true
@@ -131,15 +454,32 @@ impl Disk {
                 }
             }
             if write_pending {
-                bytes += self.write_data(reader)?;
+                bytes += self.write_data(reader, &mut warnings)?;
                 write_pending = false;
             }
         }
-        unsafe {
-            match ffi::archive_write_finish_entry(self.handle()) {
-                ffi::ARCHIVE_OK => Ok(bytes),
-                _ => Err(ArchiveError::from(self as &dyn Handle)),
-            }
+        let code = unsafe { ffi::archive_write_finish_entry(self.handle()) };
+        if let Some(warning) = self.classify(code)? {
+            warnings.push(warning);
+        }
+        Ok((bytes, warnings))
+    }
+
+    // Classify a libarchive return code against a write: `ARCHIVE_OK` is clean,
+    // `ARCHIVE_WARN` yields a collectable warning carrying the real status class, and
+    // anything else is returned as an error that likewise records its true class
+    // (`Failed`, `Retry` or `Fatal`).
+    fn classify(&self, code: i32) -> ArchiveResult<Option<ArchiveError>> {
+        match code {
+            ffi::ARCHIVE_OK => Ok(None),
+            ffi::ARCHIVE_WARN => Ok(Some(ArchiveError::from_status(
+                self as &dyn Handle,
+                Status::from_code(code),
+            ))),
+            _ => Err(ArchiveError::from_status(
+                self as &dyn Handle,
+                Status::from_code(code),
+            )),
         }
     }
 
@@ -152,7 +492,11 @@ impl Disk {
         }
     }
 
-    fn write_data<T: Reader>(&self, reader: &T) -> ArchiveResult<usize> {
+    fn write_data<T: Reader>(
+        &self,
+        reader: &T,
+        warnings: &mut Vec<ArchiveError>,
+    ) -> ArchiveResult<usize> {
         let mut buff = ptr::null();
         let mut size = 0;
         let mut offset = 0;
@@ -167,10 +511,9 @@ impl Disk {
                 ) {
                     ffi::ARCHIVE_EOF => return Ok(size),
                     ffi::ARCHIVE_OK => {
-                        if ffi::archive_write_data_block(self.handle, buff, size, offset)
-                            != ffi::ARCHIVE_OK as isize
-                        {
-                            return Err(ArchiveError::from(self as &dyn Handle));
+                        let code = ffi::archive_write_data_block(self.handle, buff, size, offset);
+                        if let Some(warning) = self.classify(code as i32)? {
+                            warnings.push(warning);
                         }
                     }
                     _ => return Err(ArchiveError::from(reader as &dyn Handle)),
@@ -179,13 +522,9 @@ impl Disk {
         }
     }
 
-    fn write_header(&self, entry: &ReaderEntry) -> ArchiveResult<()> {
-        unsafe {
-            match ffi::archive_write_header(self.handle, entry.entry()) {
-                ffi::ARCHIVE_OK => Ok(()),
-                _ => ArchiveResult::from(self as &dyn Handle),
-            }
-        }
+    fn write_header(&self, entry: &ReaderEntry) -> ArchiveResult<Option<ArchiveError>> {
+        let code = unsafe { ffi::archive_write_header(self.handle, entry.entry()) };
+        self.classify(code)
     }
 }
 
@@ -232,6 +571,7 @@ impl Builder {
             WriteFilter::Lrzip => unsafe { ffi::archive_write_add_filter_lrzip(self.handle) },
             WriteFilter::Lzip => unsafe { ffi::archive_write_add_filter_lzip(self.handle) },
             WriteFilter::Lzma => unsafe { ffi::archive_write_add_filter_lzma(self.handle) },
+            WriteFilter::Lz4 => unsafe { ffi::archive_write_add_filter_lz4(self.handle) },
             WriteFilter::Lzop => unsafe { ffi::archive_write_add_filter_lzop(self.handle) },
             WriteFilter::None => unsafe { ffi::archive_write_add_filter_none(self.handle) },
             WriteFilter::Program(prog) => {
@@ -240,6 +580,38 @@ impl Builder {
             }
             WriteFilter::UuEncode => unsafe { ffi::archive_write_add_filter_uuencode(self.handle) },
             WriteFilter::Xz => unsafe { ffi::archive_write_add_filter_xz(self.handle) },
+            WriteFilter::Zstd { level } => {
+                let res = unsafe { ffi::archive_write_add_filter_zstd(self.handle) };
+                if res != ffi::ARCHIVE_OK {
+                    return Err(ArchiveError::from_status(
+                        self as &dyn Handle,
+                        Status::from_code(res),
+                    ));
+                }
+                // libarchive only accepts the compression level through the filter's
+                // option string, so it has to be applied once the filter is in place. A
+                // rejected level may leave `errno` unset, so branch on the return code
+                // directly rather than letting it fall through to the shared
+                // `ArchiveResult::from`, which would report it as success.
+                let module = CString::new("zstd").unwrap();
+                let key = CString::new("compression-level").unwrap();
+                let value = CString::new(level.to_string()).unwrap();
+                let code = unsafe {
+                    ffi::archive_write_set_filter_option(
+                        self.handle,
+                        module.as_ptr(),
+                        key.as_ptr(),
+                        value.as_ptr(),
+                    )
+                };
+                if code != ffi::ARCHIVE_OK {
+                    return Err(ArchiveError::from_status(
+                        self as &dyn Handle,
+                        Status::from_code(code),
+                    ));
+                }
+                code
+            }
         };
         match result {
             ffi::ARCHIVE_OK => Ok(()),
@@ -281,6 +653,42 @@ impl Builder {
         }
     }
 
+    // Set a single format or filter option. Most of libarchive's tunables are only
+    // reachable through its option strings (e.g. `zip:compression=store`,
+    // `gzip:!timestamp`), so the module/key/value are assembled into a
+    // `"module:key=value"` string and handed to `archive_write_set_options`, which
+    // routes it to the right format or filter. A `value` of `None` sets a bare
+    // boolean option such as `zip:experimental`.
+    pub fn set_option(
+        &self,
+        module: Option<&str>,
+        key: &str,
+        value: Option<&str>,
+    ) -> ArchiveResult<()> {
+        let mut option = String::new();
+        if let Some(module) = module {
+            option.push_str(module);
+            option.push(':');
+        }
+        option.push_str(key);
+        if let Some(value) = value {
+            option.push('=');
+            option.push_str(value);
+        }
+        let c_option = CString::new(option).unwrap();
+        // A rejected option (`ARCHIVE_WARN`/`ARCHIVE_FAILED`) may leave `errno` unset,
+        // so branch on the return code directly rather than going through
+        // `ArchiveResult::from`, which would report success whenever `err_code() == 0`.
+        let code = unsafe { ffi::archive_write_set_options(self.handle, c_option.as_ptr()) };
+        match code {
+            ffi::ARCHIVE_OK => Ok(()),
+            _ => Err(ArchiveError::from_status(
+                self as &dyn Handle,
+                Status::from_code(code),
+            )),
+        }
+    }
+
     pub fn open_file<T: AsRef<Path>>(mut self, file: T) -> ArchiveResult<Writer> {
         if self.consumed {
             return Err(ArchiveError::Consumed);
@@ -295,6 +703,52 @@ impl Builder {
             _ => Err(ArchiveError::from(&self as &dyn Handle)),
         }
     }
+
+    // Stream the archive into an arbitrary `io::Write` instead of a file on disk. The
+    // writer is boxed and handed to libarchive as the callback `client_data`; the
+    // returned `Writer` owns that box and frees it when dropped. The `'static` bound is
+    // required for soundness: the returned `Writer` keeps the writer alive behind a raw
+    // pointer, so a borrowed writer could otherwise dangle.
+    pub fn open_stream<W: Write + 'static>(mut self, writer: W) -> ArchiveResult<Writer> {
+        if self.consumed {
+            return Err(ArchiveError::Consumed);
+        }
+        let error: *mut Option<io::Error> = Box::into_raw(Box::new(None));
+        let state = Box::new(StreamData {
+            writer: writer,
+            error: error,
+        });
+        let data = Box::into_raw(state) as *mut c_void;
+        let res = unsafe {
+            ffi::archive_write_open(
+                self.handle,
+                data,
+                stream_open_callback,
+                stream_write_callback::<W>,
+                stream_close_callback::<W>,
+            )
+        };
+        match res {
+            ffi::ARCHIVE_OK => {
+                self.consumed = true;
+                Ok(Writer::with_client(
+                    self.handle,
+                    StreamClient {
+                        data: data,
+                        error: error,
+                        free: free_stream::<W>,
+                    },
+                ))
+            }
+            _ => {
+                unsafe {
+                    free_stream::<W>(data);
+                    drop(Box::from_raw(error));
+                }
+                Err(ArchiveError::from(&self as &dyn Handle))
+            }
+        }
+    }
 }
 
 impl Default for Builder {